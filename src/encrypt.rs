@@ -1,23 +1,38 @@
 use std::{fs::File, io::{BufReader, Read}};
 use aes_gcm::{Key, Aes256Gcm, Nonce};
 use aes_gcm::aead::{Aead, NewAead};
-use anyhow::{Result, Ok, Context};
+use anyhow::{Result, Ok, Context, anyhow};
 use rand::{Rng, prelude::StdRng, SeedableRng, RngCore};
 use std::num::NonZeroU32;
 use crate::structs::*;
 
+// `-` for `input` means "read from stdin" rather than a real file, so dexios can sit
+// in a shell pipeline, e.g. `tar -c dir | dexios encrypt -`
 pub fn encrypt_file(input: &str, output: &str, keyfile: &str) -> Result<()> {
     let mut use_keyfile = false;
     if !keyfile.is_empty() { use_keyfile = true; }
 
-    let file = File::open(input).context("Unable to open the input file")?;
-    let mut reader = BufReader::new(file);
+    let stdin_input = input == "-";
+
     let mut data = Vec::new(); // our file bytes
-    reader.read_to_end(&mut data).context("Unable to read the input file")?;
+    if stdin_input {
+        std::io::stdin().read_to_end(&mut data).context("Unable to read stdin")?;
+    } else {
+        let file = File::open(input).context("Unable to open the input file")?;
+        let mut reader = BufReader::new(file);
+        reader.read_to_end(&mut data).context("Unable to read the input file")?;
+    }
 
     let raw_key;
 
     if !use_keyfile { // if we're not using a keyfile, read from stdin
+        // stdin is already spoken for by the piped data above - there's nothing left
+        // to interactively prompt on, so require a keyfile instead
+        if stdin_input {
+            return Err(anyhow!(
+                "A keyfile is required when reading the input from stdin (no password prompt available)"
+            ));
+        }
         loop {
             let input = rpassword::prompt_password("Password: ").context("Unable to read password")?;
             let input_validation = rpassword::prompt_password("Password (for validation): ").context("Unable to read password")?;
@@ -27,7 +42,7 @@ pub fn encrypt_file(input: &str, output: &str, keyfile: &str) -> Result<()> {
             } else { println!("The passwords aren't the same, please try again."); }
         }
     } else {
-        let file = File::open(input).context("Error opening keyfile")?;
+        let file = File::open(keyfile).context("Error opening keyfile")?;
         let mut reader = BufReader::new(file);
         let mut buffer = Vec::new(); // our file bytes
         reader.read_to_end(&mut buffer).context("Error reading keyfile")?;
@@ -51,9 +66,13 @@ pub fn encrypt_file(input: &str, output: &str, keyfile: &str) -> Result<()> {
     let nonce_base64 = base64::encode(nonce);
 
     let data = DexiosFile{ salt: salt_base64, nonce: nonce_base64, data: encrypted_bytes_base64 };
-    
-    let writer = File::create(output).context("Can't create output file")?; // add error handling (e.g. can't create file)
-    serde_json::to_writer(&writer, &data).context("Can't write to the output file")?; // error = can't write to file
+
+    if output == "-" {
+        serde_json::to_writer(std::io::stdout(), &data).context("Can't write to stdout")?;
+    } else {
+        let writer = File::create(output).context("Can't create output file")?; // add error handling (e.g. can't create file)
+        serde_json::to_writer(&writer, &data).context("Can't write to the output file")?; // error = can't write to file
+    }
 
     Ok(())
 }
\ No newline at end of file
@@ -1,8 +1,8 @@
-use crate::global::enums::{Algorithm, BenchMode, CipherMode, HashMode, OutputFile};
-use crate::global::structs::{Header, HeaderType};
+use crate::global::enums::{Algorithm, BenchMode, CipherMode, HashMode, InputFile, OutputFile};
+use crate::global::structs::{ArgonParams, Header, HeaderType, Keyslot, KEYSLOT_COUNT};
 use crate::global::{BLOCK_SIZE, VERSION};
 use crate::header::sign;
-use crate::key::{argon2_hash, gen_salt};
+use crate::key::gen_salt;
 use crate::secret::Secret;
 use crate::streams::init_encryption_stream;
 use aead::{Aead, NewAead};
@@ -12,9 +12,8 @@ use anyhow::Context;
 use anyhow::Result;
 use chacha20poly1305::{XChaCha20Poly1305, XNonce};
 use deoxys::DeoxysII256;
-use paris::success;
+use paris::Logger;
 use rand::{prelude::StdRng, Rng, SeedableRng};
-use std::fs::File;
 use std::io::Read;
 use std::result::Result::Ok;
 use std::time::Instant;
@@ -30,8 +29,15 @@ pub fn encrypt_bytes_memory_mode(
     bench: BenchMode,
     hash: HashMode,
     algorithm: Algorithm,
+    argon_params: ArgonParams,
+    metadata: Option<Secret<Vec<u8>>>,
 ) -> Result<()> {
-    let salt = gen_salt();
+    crate::key::validate_params(&argon_params)?;
+
+    // the data is encrypted with a fresh random master key, never with a credential-derived
+    // key directly - that lets `dexios key add`/`key remove` change who can unlock a file
+    // without touching the ciphertext
+    let master_key = crate::key::gen_master_key();
 
     let header_type = HeaderType {
         header_version: VERSION,
@@ -39,6 +45,26 @@ pub fn encrypt_bytes_memory_mode(
         algorithm,
     };
 
+    let keyslot = crate::header::wrap_master_key(
+        &master_key,
+        raw_key,
+        header_type.header_version,
+        argon_params,
+    )?;
+    let mut keyslots = [Keyslot::empty(); KEYSLOT_COUNT];
+    keyslots[0] = keyslot;
+
+    let salt = gen_salt();
+
+    // split the master key into a subkey that only ever touches the AEAD cipher, and one
+    // that only ever touches the header signature - see `crate::key::derive_subkeys`
+    let (aead_key, mac_key) = crate::key::derive_subkeys(&master_key, &salt)?;
+    drop(master_key);
+
+    let encrypted_metadata = metadata
+        .map(|metadata| crate::header::encrypt_metadata(metadata, &aead_key))
+        .transpose()?;
+
     let (header, signature, encrypted_bytes) = match algorithm {
         Algorithm::Aes256Gcm => {
             let nonce_bytes = StdRng::from_entropy().gen::<[u8; 12]>();
@@ -48,18 +74,19 @@ pub fn encrypt_bytes_memory_mode(
                 salt,
                 nonce: nonce_bytes.to_vec(),
                 header_type,
+                argon_params,
+                keyslots,
+                metadata: encrypted_metadata,
             };
-        
-            let key = argon2_hash(raw_key, &salt, &header.header_type.header_version)?;
 
-            let cipher = match Aes256Gcm::new_from_slice(key.expose()) {
+            let cipher = match Aes256Gcm::new_from_slice(aead_key.expose()) {
                 Ok(cipher) => {
                     cipher
                 }
-                Err(_) => return Err(anyhow!("Unable to create cipher with argon2id hashed key.")),
+                Err(_) => return Err(anyhow!("Unable to create cipher with the derived AEAD key.")),
             };
 
-            let signature = sign(&header, key)?;
+            let signature = sign(&header, mac_key)?;
 
             let encrypted_bytes = match cipher.encrypt(nonce, data.expose().as_slice()) {
                 Ok(bytes) => bytes,
@@ -78,19 +105,19 @@ pub fn encrypt_bytes_memory_mode(
                 salt,
                 nonce: nonce_bytes.to_vec(),
                 header_type,
+                argon_params,
+                keyslots,
+                metadata: encrypted_metadata,
             };
 
-            let key = argon2_hash(raw_key, &salt, &header.header_type.header_version)?;
-
-
-            let cipher = match XChaCha20Poly1305::new_from_slice(key.expose()) {
+            let cipher = match XChaCha20Poly1305::new_from_slice(aead_key.expose()) {
                 Ok(cipher) => {
                     cipher
                 }
-                Err(_) => return Err(anyhow!("Unable to create cipher with argon2id hashed key.")),
+                Err(_) => return Err(anyhow!("Unable to create cipher with the derived AEAD key.")),
             };
 
-            let signature = sign(&header, key)?;
+            let signature = sign(&header, mac_key)?;
 
             let encrypted_bytes = match cipher.encrypt(nonce, data.expose().as_slice()) {
                 Ok(bytes) => bytes,
@@ -109,18 +136,19 @@ pub fn encrypt_bytes_memory_mode(
                 salt,
                 nonce: nonce_bytes.to_vec(),
                 header_type,
+                argon_params,
+                keyslots,
+                metadata: encrypted_metadata,
             };
 
-            let key = argon2_hash(raw_key, &salt, &header.header_type.header_version)?;
-
-            let cipher = match DeoxysII256::new_from_slice(key.expose()) {
+            let cipher = match DeoxysII256::new_from_slice(aead_key.expose()) {
                 Ok(cipher) => {
                     cipher
                 }
-                Err(_) => return Err(anyhow!("Unable to create cipher with argon2id hashed key.")),
+                Err(_) => return Err(anyhow!("Unable to create cipher with the derived AEAD key.")),
             };
 
-            let signature = sign(&header, key)?;
+            let signature = sign(&header, mac_key)?;
 
             let encrypted_bytes = match cipher.encrypt(nonce, data.expose().as_slice()) {
                 Ok(bytes) => bytes,
@@ -138,7 +166,10 @@ pub fn encrypt_bytes_memory_mode(
         crate::header::write_to_file(output, &header, Some(signature.clone()))?;
         output.write_all(&encrypted_bytes)?;
         let write_duration = write_start_time.elapsed();
-        success!("Wrote to file [took {:.2}s]", write_duration.as_secs_f32());
+        Logger::new().stderr().success(format!(
+            "Wrote to file [took {:.2}s]",
+            write_duration.as_secs_f32()
+        ));
     }
 
     let mut hasher = blake3::Hasher::new();
@@ -148,11 +179,11 @@ pub fn encrypt_bytes_memory_mode(
         hasher.update(&encrypted_bytes);
         let hash = hasher.finalize().to_hex().to_string();
         let hash_duration = hash_start_time.elapsed();
-        success!(
+        Logger::new().stderr().success(format!(
             "Hash of the encrypted file is: {} [took {:.2}s]",
             hash,
             hash_duration.as_secs_f32()
-        );
+        ));
     }
 
     Ok(())
@@ -163,21 +194,28 @@ pub fn encrypt_bytes_memory_mode(
 // it gets the nonce, salt and streams enum from `init_encryption_stream` and then reads the file in blocks
 // on each read, it encrypts, writes (if enabled), hashes (if enabled) and repeats until EOF
 // it also handles the prep of each individual stream, via the match statement
+// `input` reads from a real file or from stdin (piped data), via `InputFile`; `output`
+// writes to a real file or stdout the same way, via `OutputFile`
 pub fn encrypt_bytes_stream_mode(
-    input: &mut File,
+    input: &mut InputFile,
     output: &mut OutputFile,
     raw_key: Secret<Vec<u8>>,
     bench: BenchMode,
     hash: HashMode,
     algorithm: Algorithm,
+    argon_params: ArgonParams,
+    metadata: Option<Secret<Vec<u8>>>,
 ) -> Result<()> {
+    crate::key::validate_params(&argon_params)?;
+
     let header_type = HeaderType {
         header_version: VERSION,
         cipher_mode: CipherMode::StreamMode,
         algorithm,
     };
 
-    let (mut streams, header, signature) = init_encryption_stream(raw_key, header_type)?;
+    let (mut streams, header, signature) =
+        init_encryption_stream(raw_key, header_type, argon_params, metadata)?;
 
     if bench == BenchMode::WriteToFilesystem {
         crate::header::write_to_file(output, &header, Some(signature.clone()))?;
@@ -192,9 +230,21 @@ pub fn encrypt_bytes_stream_mode(
     let mut buffer = [0u8; BLOCK_SIZE];
 
     loop {
-        let read_count = input
-            .read(&mut buffer)
-            .context("Unable to read from the input file")?;
+        // a single `read` call isn't guaranteed to fill `buffer` - a real file does in
+        // practice, but a pipe (`InputFile::Stdin`) hands back only what's currently
+        // buffered, often far less than `BLOCK_SIZE`. Keep reading until the buffer is
+        // full or we hit true EOF (`n == 0`), so a short pipe read isn't mistaken for
+        // the final block
+        let mut read_count = 0;
+        while read_count < BLOCK_SIZE {
+            let n = input
+                .read(&mut buffer[read_count..])
+                .context("Unable to read from the input file")?;
+            if n == 0 {
+                break;
+            }
+            read_count += n;
+        }
         if read_count == BLOCK_SIZE {
             let encrypted_data = match streams.encrypt_next(buffer.as_slice()) {
                 Ok(bytes) => bytes,
@@ -232,7 +282,7 @@ pub fn encrypt_bytes_stream_mode(
     }
     if hash == HashMode::CalculateHash {
         let hash = hasher.finalize().to_hex().to_string();
-        success!("Hash of the encrypted file is: {}", hash,);
+        Logger::new().stderr().success(format!("Hash of the encrypted file is: {}", hash));
     }
     Ok(())
 }
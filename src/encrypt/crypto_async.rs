@@ -0,0 +1,197 @@
+// an async, pipelined counterpart to `encrypt_bytes_stream_mode`. The sync version is a
+// strictly serial read -> encrypt -> write -> hash loop, which leaves the disk idle while
+// the CPU is encrypting and vice versa; here reading, writing and hashing each run on their
+// own tokio task connected by bounded channels, so they overlap instead. Encryption itself
+// stays sequential (the STREAM AEAD construction requires blocks to be fed to
+// `encrypt_next`/`encrypt_last` in order) - only the I/O either side of it is concurrent.
+// Gated behind the `async` feature so the synchronous API and its dependency-free build
+// are unaffected.
+#![cfg(feature = "async")]
+
+use crate::global::enums::{Algorithm, BenchMode, CipherMode, HashMode};
+use crate::global::structs::{ArgonParams, HeaderType};
+use crate::global::{BLOCK_SIZE, VERSION};
+use crate::secret::Secret;
+use crate::streams::init_encryption_stream;
+use anyhow::{anyhow, Context, Result};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+// one block off the input, tagged with whether it's the final (possibly short) block -
+// the same short-read condition the sync loop uses to find `encrypt_last`
+struct Block {
+    bytes: Vec<u8>,
+    last: bool,
+}
+
+// reads `input` into `BLOCK_SIZE` chunks and forwards them, in order, down `tx`
+async fn read_blocks<R: AsyncRead + Unpin>(mut input: R, tx: mpsc::Sender<Block>) -> Result<()> {
+    loop {
+        let mut buffer = vec![0u8; BLOCK_SIZE];
+        let mut read = 0;
+
+        // a single `read` call isn't guaranteed to fill the buffer, so keep reading until
+        // it's full or exhausted - otherwise a short read here could be mistaken for the
+        // final block when more data is still on its way
+        while read < BLOCK_SIZE {
+            let n = input
+                .read(&mut buffer[read..])
+                .await
+                .context("Unable to read from the input stream")?;
+            if n == 0 {
+                break;
+            }
+            read += n;
+        }
+
+        buffer.truncate(read);
+        let last = read != BLOCK_SIZE;
+
+        if tx.send(Block { bytes: buffer, last }).await.is_err() {
+            // the encrypt stage ended early (it already returned an error) - nothing left to do
+            return Ok(());
+        }
+
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+// encrypts data in stream mode, overlapping reading, writing and hashing via async tasks.
+// `input`/`output` are generic over any `AsyncRead`/`AsyncWrite`, so this works equally well
+// against a file, a socket, or piped stdin/stdout
+pub async fn encrypt_bytes_stream_mode_async<R, W>(
+    input: R,
+    mut output: W,
+    raw_key: Secret<Vec<u8>>,
+    bench: BenchMode,
+    hash: HashMode,
+    algorithm: Algorithm,
+    argon_params: ArgonParams,
+    metadata: Option<Secret<Vec<u8>>>,
+) -> Result<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    crate::key::validate_params(&argon_params)?;
+
+    let header_type = HeaderType {
+        header_version: VERSION,
+        cipher_mode: CipherMode::StreamMode,
+        algorithm,
+    };
+
+    let (mut streams, header, signature) =
+        init_encryption_stream(raw_key, header_type, argon_params, metadata)?;
+
+    // the header is authenticated as a whole, so fold it into the hash up front - the
+    // hashing task below only ever sees the encrypted blocks that come after it
+    let mut hasher = blake3::Hasher::new();
+    if hash == HashMode::CalculateHash {
+        crate::header::hash(&mut hasher, &header, Some(signature.clone()));
+    }
+
+    if bench == BenchMode::WriteToFilesystem {
+        // `header::write_to_file` only needs a plain `impl Write`, so serialize into an
+        // in-memory buffer first and hand that to the async writer - there's no async
+        // equivalent of the header serialization, and there doesn't need to be
+        let mut header_bytes = Vec::new();
+        crate::header::write_to_file(&mut header_bytes, &header, Some(signature))?;
+        output
+            .write_all(&header_bytes)
+            .await
+            .context("Unable to write the header to the output stream")?;
+    }
+
+    // bounded so a slow encrypt/write/hash stage applies backpressure instead of buffering
+    // the entire file in memory
+    let (read_tx, mut read_rx) = mpsc::channel::<Block>(4);
+    let (write_tx, mut write_rx) = mpsc::channel::<Arc<Vec<u8>>>(4);
+    let (hash_tx, mut hash_rx) = mpsc::channel::<Arc<Vec<u8>>>(4);
+
+    let reader_task = tokio::spawn(read_blocks(input, read_tx));
+
+    let writer_task: tokio::task::JoinHandle<Result<()>> = tokio::spawn(async move {
+        while let Some(bytes) = write_rx.recv().await {
+            output
+                .write_all(&bytes)
+                .await
+                .context("Unable to write to the output stream")?;
+        }
+        output
+            .flush()
+            .await
+            .context("Unable to flush the output stream")?;
+        Ok(())
+    });
+
+    let hasher_task: tokio::task::JoinHandle<blake3::Hasher> = tokio::spawn(async move {
+        while let Some(bytes) = hash_rx.recv().await {
+            hasher.update(&bytes);
+        }
+        hasher
+    });
+
+    // the encrypt stage itself: strictly sequential, since the STREAM construction is
+    // order-dependent, but it now only ever waits on a channel recv rather than a raw
+    // filesystem read, so it stays busy while the reader/writer/hasher tasks work ahead of
+    // and behind it
+    while let Some(block) = read_rx.recv().await {
+        if block.last {
+            let encrypted = Arc::new(streams.encrypt_last(&block.bytes)?);
+
+            if bench == BenchMode::WriteToFilesystem {
+                write_tx
+                    .send(Arc::clone(&encrypted))
+                    .await
+                    .map_err(|_| anyhow!("The writer task ended early"))?;
+            }
+            if hash == HashMode::CalculateHash {
+                hash_tx
+                    .send(encrypted)
+                    .await
+                    .map_err(|_| anyhow!("The hashing task ended early"))?;
+            }
+
+            break;
+        }
+
+        let encrypted = Arc::new(streams.encrypt_next(&block.bytes)?);
+
+        if bench == BenchMode::WriteToFilesystem {
+            write_tx
+                .send(Arc::clone(&encrypted))
+                .await
+                .map_err(|_| anyhow!("The writer task ended early"))?;
+        }
+        if hash == HashMode::CalculateHash {
+            hash_tx
+                .send(encrypted)
+                .await
+                .map_err(|_| anyhow!("The hashing task ended early"))?;
+        }
+    }
+
+    drop(write_tx);
+    drop(hash_tx);
+
+    reader_task
+        .await
+        .context("The reader task panicked")??;
+    writer_task
+        .await
+        .context("The writer task panicked")??;
+    let mut hasher = hasher_task.await.context("The hashing task panicked")?;
+
+    if hash == HashMode::CalculateHash {
+        let hash = hasher.finalize().to_hex().to_string();
+        paris::Logger::new()
+            .stderr()
+            .success(format!("Hash of the encrypted file is: {}", hash));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use std::io::{Read, Write};
+
+// the algorithms that dexios is able to encrypt/decrypt with
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Algorithm {
+    Aes256Gcm,
+    XChaCha20Poly1305,
+    DeoxysII256,
+}
+
+// identifies which header layout a file was written with, so older files keep
+// decrypting correctly as the format gains new sections
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum HeaderVersion {
+    V1,
+    V2,
+    V3,
+    // introduces the keyslot subsystem - the data is encrypted with a random master key,
+    // and each keyslot wraps a copy of it under a different credential
+    V4,
+    // HKDF-SHA256 splits the master key into an AEAD subkey and a header-signing subkey,
+    // instead of using the master key directly for both
+    V5,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum CipherMode {
+    MemoryMode,
+    StreamMode,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum BenchMode {
+    WriteToFilesystem,
+    BenchmarkInMemory,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum HashMode {
+    CalculateHash,
+    NoHash,
+}
+
+// lets the memory/stream encrypt paths write to a real file or straight to stdout
+pub enum OutputFile {
+    File(std::fs::File),
+    Stdout(std::io::Stdout),
+}
+
+impl std::io::Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputFile::File(file) => file.write(buf),
+            OutputFile::Stdout(stdout) => stdout.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputFile::File(file) => file.flush(),
+            OutputFile::Stdout(stdout) => stdout.flush(),
+        }
+    }
+}
+
+// the input-side equivalent of `OutputFile`, so the stream encrypt/decrypt paths can read
+// from a real file or from stdin (e.g. `tar -c dir | dexios encrypt - -o out.dxio`)
+pub enum InputFile {
+    File(std::fs::File),
+    Stdin(std::io::Stdin),
+}
+
+impl std::io::Read for InputFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            InputFile::File(file) => file.read(buf),
+            InputFile::Stdin(stdin) => stdin.lock().read(buf),
+        }
+    }
+}
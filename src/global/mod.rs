@@ -0,0 +1,9 @@
+pub mod enums;
+pub mod states;
+pub mod structs;
+
+use enums::HeaderVersion;
+
+pub const BLOCK_SIZE: usize = 1_048_576;
+pub const SALT_LEN: usize = 16;
+pub const VERSION: HeaderVersion = HeaderVersion::V5;
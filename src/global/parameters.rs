@@ -1,15 +1,22 @@
 // this file handles getting parameters from clap's ArgMatches
 // it returns information (e.g. CryptoParams) to functions that require it
 
+use crate::global::enums::{InputFile, OutputFile};
 use crate::global::states::{EraseMode, HashMode, HeaderFile, KeyFile, PasswordMode, SkipMode};
-use crate::global::structs::CryptoParams;
+use crate::global::structs::{ArgonParams, CryptoParams, FileMetadata};
+use crate::secret::Secret;
 use anyhow::{Context, Result};
 use clap::ArgMatches;
 use dexios_core::primitives::Algorithm;
 use paris::warn;
+use std::fs::File;
 
 use dexios_core::primitives::ALGORITHMS;
 
+// the sigil that means "stdin"/"stdout" instead of a real path, matching the
+// convention used by most other CLI tools that sit in a shell pipeline
+const PIPE_SIGIL: &str = "-";
+
 pub fn get_param(name: &str, sub_matches: &ArgMatches) -> Result<String> {
     let value = sub_matches
         .value_of(name)
@@ -18,6 +25,39 @@ pub fn get_param(name: &str, sub_matches: &ArgMatches) -> Result<String> {
     Ok(value)
 }
 
+// opens `name` for reading, honouring `-` as stdin so dexios can sit in a shell
+// pipeline (e.g. `tar -c dir | dexios encrypt -`)
+pub fn open_input(name: &str, sub_matches: &ArgMatches) -> Result<InputFile> {
+    let path = get_param(name, sub_matches)?;
+
+    if path == PIPE_SIGIL {
+        Ok(InputFile::Stdin(std::io::stdin()))
+    } else {
+        let file = File::open(&path).with_context(|| format!("Unable to open {}", path))?;
+        Ok(InputFile::File(file))
+    }
+}
+
+// opens `name` for writing, honouring `-` as stdout so dexios can sit in a shell
+// pipeline (e.g. `dexios encrypt file.txt -o - | nc ...`)
+pub fn open_output(name: &str, sub_matches: &ArgMatches) -> Result<OutputFile> {
+    let path = get_param(name, sub_matches)?;
+
+    if path == PIPE_SIGIL {
+        Ok(OutputFile::Stdout(std::io::stdout()))
+    } else {
+        let file = File::create(&path).with_context(|| format!("Unable to create {}", path))?;
+        Ok(OutputFile::File(file))
+    }
+}
+
+// true if `name` resolves to the stdin/stdout pipe sigil, so callers can tell when
+// they shouldn't fall back to an interactive password prompt (stdin is already
+// spoken for by the piped data)
+pub fn is_pipe_mode(name: &str, sub_matches: &ArgMatches) -> bool {
+    matches!(sub_matches.value_of(name), Some(PIPE_SIGIL))
+}
+
 pub fn parameter_handler(sub_matches: &ArgMatches) -> Result<CryptoParams> {
     let keyfile = if sub_matches.is_present("keyfile") {
         KeyFile::Some(
@@ -96,6 +136,99 @@ pub fn encrypt_additional_params(sub_matches: &ArgMatches) -> Result<Algorithm>
     }
 }
 
+// reads the `--kdf-memory`/`--kdf-iterations`/`--kdf-parallelism` flags, falling back to
+// dexios' default cost parameters for any that weren't provided. Returns `None` only if
+// the user didn't touch any of the three, so callers can tell "use the defaults" apart
+// from "the user explicitly asked for the defaults"
+pub fn kdf_params_handler(sub_matches: &ArgMatches) -> Result<Option<ArgonParams>> {
+    let memory_provided = sub_matches.is_present("kdf-memory");
+    let iterations_provided = sub_matches.is_present("kdf-iterations");
+    let parallelism_provided = sub_matches.is_present("kdf-parallelism");
+
+    if !memory_provided && !iterations_provided && !parallelism_provided {
+        return Ok(None);
+    }
+
+    let m_cost = if memory_provided {
+        sub_matches
+            .value_of("kdf-memory")
+            .context("Error reading value of --kdf-memory")?
+            .parse()
+            .context("--kdf-memory must be a positive integer, in KiB")?
+    } else {
+        1_048_576
+    };
+
+    let t_cost = if iterations_provided {
+        sub_matches
+            .value_of("kdf-iterations")
+            .context("Error reading value of --kdf-iterations")?
+            .parse()
+            .context("--kdf-iterations must be a positive integer")?
+    } else {
+        10
+    };
+
+    let parallelism = if parallelism_provided {
+        sub_matches
+            .value_of("kdf-parallelism")
+            .context("Error reading value of --kdf-parallelism")?
+            .parse()
+            .context("--kdf-parallelism must be a positive integer")?
+    } else {
+        4
+    };
+
+    let params = ArgonParams {
+        t_cost,
+        m_cost,
+        parallelism,
+    };
+
+    crate::key::validate_params(&params)?;
+
+    Ok(Some(params))
+}
+
+// builds the (serde_json-serialized) metadata that should be encrypted into the header,
+// from `--metadata` (a free-form note) and/or `--store-name` (stashes the input filename).
+// Returns `None` when neither flag was given, so callers can skip the metadata section
+// entirely rather than writing an empty-but-present one
+pub fn metadata_params_handler(
+    sub_matches: &ArgMatches,
+    input_name: &str,
+) -> Result<Option<Secret<Vec<u8>>>> {
+    let note_provided = sub_matches.is_present("metadata");
+    let store_name = sub_matches.is_present("store-name");
+
+    if !note_provided && !store_name {
+        return Ok(None);
+    }
+
+    let metadata = FileMetadata {
+        file_name: if store_name {
+            Some(input_name.to_string())
+        } else {
+            None
+        },
+        note: if note_provided {
+            Some(
+                sub_matches
+                    .value_of("metadata")
+                    .context("Error reading value of --metadata")?
+                    .to_string(),
+            )
+        } else {
+            None
+        },
+    };
+
+    let serialized =
+        serde_json::to_vec(&metadata).context("Unable to serialize the file's metadata")?;
+
+    Ok(Some(Secret::new(serialized)))
+}
+
 pub fn decrypt_additional_params(sub_matches: &ArgMatches) -> Result<HeaderFile> {
     let header = if sub_matches.is_present("header") {
         HeaderFile::Some(
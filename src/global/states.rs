@@ -0,0 +1,31 @@
+pub use super::enums::HashMode;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SkipMode {
+    ShowPrompts,
+    HidePrompts,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum EraseMode {
+    EraseFile(i32),
+    IgnoreFile(i32),
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PasswordMode {
+    NormalKeySourcePriority,
+    ForceUserProvidedPassword,
+}
+
+#[derive(Clone, Debug)]
+pub enum KeyFile {
+    Some(String),
+    None,
+}
+
+#[derive(Clone, Debug)]
+pub enum HeaderFile {
+    Some(String),
+    None,
+}
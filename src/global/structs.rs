@@ -0,0 +1,94 @@
+use super::enums::{Algorithm, CipherMode, HeaderVersion};
+use super::states::{EraseMode, HashMode, KeyFile, PasswordMode, SkipMode};
+use super::SALT_LEN;
+use serde::{Deserialize, Serialize};
+
+// the user-facing metadata attached to a file, serialized with serde_json and then
+// encrypted into the header's metadata section
+#[derive(Serialize, Deserialize, Default)]
+pub struct FileMetadata {
+    pub file_name: Option<String>,
+    pub note: Option<String>,
+}
+
+// cost parameters for the argon2id key derivation. Stored in the header itself so a file
+// can always be decrypted with the exact parameters it was created with, rather than
+// inferring them from the header version alone
+#[derive(Clone, Copy, Debug)]
+pub struct ArgonParams {
+    pub t_cost: u32,
+    pub m_cost: u32,
+    pub parallelism: u8,
+}
+
+pub struct HeaderType {
+    pub header_version: HeaderVersion,
+    pub cipher_mode: CipherMode,
+    pub algorithm: Algorithm,
+}
+
+// how many credentials (passwords/keyfiles) a single file can be unlocked with
+pub const KEYSLOT_COUNT: usize = 4;
+pub const KEYSLOT_NONCE_LEN: usize = 24;
+// a 32-byte master key, XChaCha20Poly1305-encrypted under a slot's wrapping key
+pub const WRAPPED_KEY_LEN: usize = 48;
+
+// one wrapped copy of the file's master key. An empty slot is represented by an
+// all-zero `wrapped_key`, since a valid ciphertext of that length is never all zero bytes
+#[derive(Clone, Copy)]
+pub struct Keyslot {
+    pub salt: [u8; SALT_LEN],
+    pub nonce: [u8; KEYSLOT_NONCE_LEN],
+    pub wrapped_key: [u8; WRAPPED_KEY_LEN],
+    pub argon_params: ArgonParams,
+}
+
+impl Keyslot {
+    pub fn empty() -> Self {
+        Self {
+            salt: [0u8; SALT_LEN],
+            nonce: [0u8; KEYSLOT_NONCE_LEN],
+            wrapped_key: [0u8; WRAPPED_KEY_LEN],
+            argon_params: ArgonParams {
+                t_cost: 0,
+                m_cost: 0,
+                parallelism: 0,
+            },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.wrapped_key == [0u8; WRAPPED_KEY_LEN]
+    }
+}
+
+pub const METADATA_NONCE_LEN: usize = 24;
+
+// an encrypted, authenticated blob attached to the header - e.g. the original filename,
+// a MIME type, or a user note. `ciphertext` being present-but-empty (a zero-length
+// plaintext, still wrapped in an AEAD tag) is distinct from `Header::metadata` being `None`
+pub struct EncryptedMetadata {
+    pub nonce: [u8; METADATA_NONCE_LEN],
+    pub ciphertext: Vec<u8>,
+}
+
+pub struct Header {
+    pub salt: [u8; SALT_LEN],
+    pub nonce: Vec<u8>,
+    pub header_type: HeaderType,
+    // not used to derive anything since the keyslot subsystem landed - each keyslot carries
+    // its own `argon_params` for unwrapping the master key. Kept (and still signed) only so
+    // a file's originally-requested cost parameters stay visible/inspectable on the header
+    // itself, independent of which keyslot someone happens to unlock it with
+    pub argon_params: ArgonParams,
+    pub keyslots: [Keyslot; KEYSLOT_COUNT],
+    pub metadata: Option<EncryptedMetadata>,
+}
+
+pub struct CryptoParams {
+    pub hash_mode: HashMode,
+    pub skip: SkipMode,
+    pub password: PasswordMode,
+    pub erase: EraseMode,
+    pub keyfile: KeyFile,
+}
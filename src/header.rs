@@ -0,0 +1,442 @@
+use crate::global::enums::{Algorithm, CipherMode, HeaderVersion};
+use crate::global::structs::{
+    ArgonParams, EncryptedMetadata, Header, HeaderType, Keyslot, KEYSLOT_COUNT, KEYSLOT_NONCE_LEN,
+    METADATA_NONCE_LEN, WRAPPED_KEY_LEN,
+};
+use crate::global::SALT_LEN;
+use crate::key::argon2_hash;
+use crate::secret::Secret;
+use aead::{Aead, NewAead};
+use anyhow::{anyhow, Context, Result};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use std::io::{Read, Write};
+
+// 4 magic bytes + 1 version byte + 1 cipher-mode byte + 1 algorithm byte + salt + nonce + the
+// 9 little-endian argon2 cost bytes (t_cost: u32, m_cost: u32, parallelism: u8), followed by
+// the keyslots, the metadata section and the signature
+const MAGIC_BYTES: [u8; 4] = *b"DXIO";
+const ARGON_PARAMS_LEN: usize = 9;
+
+fn version_to_byte(version: HeaderVersion) -> u8 {
+    match version {
+        HeaderVersion::V1 => 1,
+        HeaderVersion::V2 => 2,
+        HeaderVersion::V3 => 3,
+        HeaderVersion::V4 => 4,
+        HeaderVersion::V5 => 5,
+    }
+}
+
+fn byte_to_version(byte: u8) -> Result<HeaderVersion> {
+    match byte {
+        1 => Ok(HeaderVersion::V1),
+        2 => Ok(HeaderVersion::V2),
+        3 => Ok(HeaderVersion::V3),
+        4 => Ok(HeaderVersion::V4),
+        5 => Ok(HeaderVersion::V5),
+        _ => Err(anyhow!("Unknown header version")),
+    }
+}
+
+fn cipher_mode_to_byte(mode: CipherMode) -> u8 {
+    match mode {
+        CipherMode::MemoryMode => 0,
+        CipherMode::StreamMode => 1,
+    }
+}
+
+fn byte_to_cipher_mode(byte: u8) -> Result<CipherMode> {
+    match byte {
+        0 => Ok(CipherMode::MemoryMode),
+        1 => Ok(CipherMode::StreamMode),
+        _ => Err(anyhow!("Unknown cipher mode")),
+    }
+}
+
+fn algorithm_to_byte(algorithm: Algorithm) -> u8 {
+    match algorithm {
+        Algorithm::Aes256Gcm => 0,
+        Algorithm::XChaCha20Poly1305 => 1,
+        Algorithm::DeoxysII256 => 2,
+    }
+}
+
+fn byte_to_algorithm(byte: u8) -> Result<Algorithm> {
+    match byte {
+        0 => Ok(Algorithm::Aes256Gcm),
+        1 => Ok(Algorithm::XChaCha20Poly1305),
+        2 => Ok(Algorithm::DeoxysII256),
+        _ => Err(anyhow!("Unknown algorithm")),
+    }
+}
+
+// serializes the argon2 cost parameters as 9 fixed little-endian bytes, for embedding in the header
+pub fn serialize_argon_params(params: &ArgonParams) -> [u8; ARGON_PARAMS_LEN] {
+    let mut bytes = [0u8; ARGON_PARAMS_LEN];
+    bytes[0..4].copy_from_slice(&params.t_cost.to_le_bytes());
+    bytes[4..8].copy_from_slice(&params.m_cost.to_le_bytes());
+    bytes[8] = params.parallelism;
+    bytes
+}
+
+// the inverse of `serialize_argon_params`, used when reading a header back off disk
+pub fn deserialize_argon_params(bytes: [u8; ARGON_PARAMS_LEN]) -> ArgonParams {
+    ArgonParams {
+        t_cost: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+        m_cost: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+        parallelism: bytes[8],
+    }
+}
+
+const KEYSLOT_LEN: usize = SALT_LEN + KEYSLOT_NONCE_LEN + WRAPPED_KEY_LEN + ARGON_PARAMS_LEN;
+
+fn serialize_keyslot(slot: &Keyslot) -> [u8; KEYSLOT_LEN] {
+    let mut bytes = [0u8; KEYSLOT_LEN];
+    let mut offset = 0;
+    bytes[offset..offset + SALT_LEN].copy_from_slice(&slot.salt);
+    offset += SALT_LEN;
+    bytes[offset..offset + KEYSLOT_NONCE_LEN].copy_from_slice(&slot.nonce);
+    offset += KEYSLOT_NONCE_LEN;
+    bytes[offset..offset + WRAPPED_KEY_LEN].copy_from_slice(&slot.wrapped_key);
+    offset += WRAPPED_KEY_LEN;
+    bytes[offset..offset + ARGON_PARAMS_LEN].copy_from_slice(&serialize_argon_params(&slot.argon_params));
+    bytes
+}
+
+fn deserialize_keyslot(bytes: [u8; KEYSLOT_LEN]) -> Keyslot {
+    let mut offset = 0;
+    let mut salt = [0u8; SALT_LEN];
+    salt.copy_from_slice(&bytes[offset..offset + SALT_LEN]);
+    offset += SALT_LEN;
+    let mut nonce = [0u8; KEYSLOT_NONCE_LEN];
+    nonce.copy_from_slice(&bytes[offset..offset + KEYSLOT_NONCE_LEN]);
+    offset += KEYSLOT_NONCE_LEN;
+    let mut wrapped_key = [0u8; WRAPPED_KEY_LEN];
+    wrapped_key.copy_from_slice(&bytes[offset..offset + WRAPPED_KEY_LEN]);
+    offset += WRAPPED_KEY_LEN;
+    let mut argon_params_bytes = [0u8; ARGON_PARAMS_LEN];
+    argon_params_bytes.copy_from_slice(&bytes[offset..offset + ARGON_PARAMS_LEN]);
+
+    Keyslot {
+        salt,
+        nonce,
+        wrapped_key,
+        argon_params: deserialize_argon_params(argon_params_bytes),
+    }
+}
+
+// wraps the file's master key with a credential-derived key, producing a keyslot that can
+// later be stored in the header. Each slot gets its own salt/nonce/argon2 params so slots
+// are independent of each other and of the data-encryption key
+pub fn wrap_master_key(
+    master_key: &Secret<Vec<u8>>,
+    credential: Secret<Vec<u8>>,
+    header_version: HeaderVersion,
+    argon_params: ArgonParams,
+) -> Result<Keyslot> {
+    let salt = crate::key::gen_salt();
+    let mut nonce_bytes = [0u8; KEYSLOT_NONCE_LEN];
+    StdRng::from_entropy().fill_bytes(&mut nonce_bytes);
+
+    let wrapping_key = argon2_hash(credential, &salt, &header_version, Some(&argon_params))?;
+
+    let cipher = XChaCha20Poly1305::new_from_slice(wrapping_key.expose())
+        .map_err(|_| anyhow!("Unable to create cipher to wrap the master key"))?;
+
+    let wrapped = cipher
+        .encrypt(XNonce::from_slice(&nonce_bytes), master_key.expose().as_slice())
+        .map_err(|_| anyhow!("Unable to wrap the master key"))?;
+
+    let mut wrapped_key = [0u8; WRAPPED_KEY_LEN];
+    wrapped_key.copy_from_slice(&wrapped);
+
+    Ok(Keyslot {
+        salt,
+        nonce: nonce_bytes,
+        wrapped_key,
+        argon_params,
+    })
+}
+
+// tries every non-empty keyslot against the supplied credential, returning the unwrapped
+// master key from the first slot that succeeds. A failed attempt on one slot is not an
+// error by itself - only exhausting every slot is
+pub fn unwrap_master_key(
+    keyslots: &[Keyslot; KEYSLOT_COUNT],
+    credential: Secret<Vec<u8>>,
+    header_version: HeaderVersion,
+) -> Result<Secret<Vec<u8>>> {
+    for slot in keyslots.iter().filter(|slot| !slot.is_empty()) {
+        let wrapping_key = argon2_hash(
+            credential.clone(),
+            &slot.salt,
+            &header_version,
+            Some(&slot.argon_params),
+        )?;
+
+        let cipher = match XChaCha20Poly1305::new_from_slice(wrapping_key.expose()) {
+            Ok(cipher) => cipher,
+            Err(_) => continue,
+        };
+
+        if let Ok(master_key) = cipher.decrypt(XNonce::from_slice(&slot.nonce), slot.wrapped_key.as_slice()) {
+            return Ok(Secret::new(master_key));
+        }
+    }
+
+    Err(anyhow!(
+        "None of the provided credentials could unlock this file"
+    ))
+}
+
+// finds the first unused keyslot, so `dexios key add` knows where to put a new credential
+pub fn find_empty_keyslot(keyslots: &[Keyslot; KEYSLOT_COUNT]) -> Result<usize> {
+    keyslots
+        .iter()
+        .position(Keyslot::is_empty)
+        .ok_or_else(|| anyhow!("All keyslots are full - remove one before adding another"))
+}
+
+// a malicious header could otherwise claim an enormous metadata length and force a huge
+// allocation before a single byte has been authenticated - 1MiB is far more than any
+// filename/MIME-type/note legitimately needs
+const MAX_METADATA_LEN: u32 = 1024 * 1024;
+
+// encrypts the (already serde_json-serialized) metadata bytes under `aead_key` - the
+// HKDF-derived AEAD subkey (see `crate::key::derive_subkeys`), *not* the raw master key -
+// so it's readable only by someone who can also decrypt the file itself
+pub fn encrypt_metadata(metadata: Secret<Vec<u8>>, aead_key: &Secret<Vec<u8>>) -> Result<EncryptedMetadata> {
+    let mut nonce_bytes = [0u8; METADATA_NONCE_LEN];
+    StdRng::from_entropy().fill_bytes(&mut nonce_bytes);
+
+    let cipher = XChaCha20Poly1305::new_from_slice(aead_key.expose())
+        .map_err(|_| anyhow!("Unable to create cipher to encrypt the metadata"))?;
+
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce_bytes), metadata.expose().as_slice())
+        .map_err(|_| anyhow!("Unable to encrypt the metadata"))?;
+
+    Ok(EncryptedMetadata {
+        nonce: nonce_bytes,
+        ciphertext,
+    })
+}
+
+// decrypts a header's metadata block with `aead_key` - the same HKDF-derived AEAD subkey
+// `encrypt_metadata` used, not the raw master key. Does not touch the ciphertext body, so
+// callers can read a file's metadata without streaming the whole thing
+pub fn decrypt_metadata(encrypted: &EncryptedMetadata, aead_key: &Secret<Vec<u8>>) -> Result<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new_from_slice(aead_key.expose())
+        .map_err(|_| anyhow!("Unable to create cipher to decrypt the metadata"))?;
+
+    cipher
+        .decrypt(XNonce::from_slice(&encrypted.nonce), encrypted.ciphertext.as_slice())
+        .map_err(|_| anyhow!("Unable to decrypt the metadata - wrong key, or the header has been tampered with"))
+}
+
+fn serialize_metadata(metadata: &Option<EncryptedMetadata>) -> Vec<u8> {
+    match metadata {
+        None => vec![0],
+        Some(metadata) => {
+            let mut bytes = Vec::with_capacity(1 + METADATA_NONCE_LEN + 4 + metadata.ciphertext.len());
+            bytes.push(1);
+            bytes.extend_from_slice(&metadata.nonce);
+            bytes.extend_from_slice(&(metadata.ciphertext.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&metadata.ciphertext);
+            bytes
+        }
+    }
+}
+
+fn read_metadata_section(input: &mut impl Read) -> Result<Option<EncryptedMetadata>> {
+    let mut present = [0u8; 1];
+    input
+        .read_exact(&mut present)
+        .context("Unable to read metadata presence byte")?;
+
+    if present[0] == 0 {
+        return Ok(None);
+    }
+
+    let mut nonce = [0u8; METADATA_NONCE_LEN];
+    input
+        .read_exact(&mut nonce)
+        .context("Unable to read metadata nonce")?;
+
+    let mut len_bytes = [0u8; 4];
+    input
+        .read_exact(&mut len_bytes)
+        .context("Unable to read metadata length")?;
+    let len = u32::from_le_bytes(len_bytes);
+    if len > MAX_METADATA_LEN {
+        return Err(anyhow!(
+            "Metadata section claims to be larger than the {} byte limit",
+            MAX_METADATA_LEN
+        ));
+    }
+
+    let mut ciphertext = vec![0u8; len as usize];
+    input
+        .read_exact(&mut ciphertext)
+        .context("Unable to read metadata ciphertext")?;
+
+    Ok(Some(EncryptedMetadata { nonce, ciphertext }))
+}
+
+// writes the full header (magic bytes, type tags, salt, nonce, argon2 params, keyslots,
+// metadata and signature) to the output file/stream, ahead of the ciphertext. Generic over
+// `impl Write` rather than `OutputFile` specifically, so it can also serialize a header into
+// a plain in-memory buffer (e.g. for an async writer, which isn't `std::io::Write`)
+pub fn write_to_file(output: &mut impl Write, header: &Header, signature: Option<Vec<u8>>) -> Result<()> {
+    output
+        .write_all(&MAGIC_BYTES)
+        .context("Unable to write header magic bytes")?;
+    output
+        .write_all(&[version_to_byte(header.header_type.header_version)])
+        .context("Unable to write header version")?;
+    output
+        .write_all(&[cipher_mode_to_byte(header.header_type.cipher_mode)])
+        .context("Unable to write cipher mode")?;
+    output
+        .write_all(&[algorithm_to_byte(header.header_type.algorithm)])
+        .context("Unable to write algorithm")?;
+    output
+        .write_all(&header.salt)
+        .context("Unable to write salt")?;
+    output
+        .write_all(&header.nonce)
+        .context("Unable to write nonce")?;
+    output
+        .write_all(&serialize_argon_params(&header.argon_params))
+        .context("Unable to write argon2 parameters")?;
+
+    for slot in &header.keyslots {
+        output
+            .write_all(&serialize_keyslot(slot))
+            .context("Unable to write keyslot")?;
+    }
+
+    output
+        .write_all(&serialize_metadata(&header.metadata))
+        .context("Unable to write metadata")?;
+
+    if let Some(signature) = signature {
+        output
+            .write_all(&signature)
+            .context("Unable to write header signature")?;
+    }
+
+    Ok(())
+}
+
+// the AEAD nonce length for each algorithm, in memory mode. Stream mode nonces are 4 bytes
+// shorter since the STREAM construction reserves the last 4 bytes of the counter
+pub fn nonce_len_for(algorithm: Algorithm, cipher_mode: CipherMode) -> usize {
+    let memory_mode_len = match algorithm {
+        Algorithm::Aes256Gcm => 12,
+        Algorithm::XChaCha20Poly1305 => 24,
+        Algorithm::DeoxysII256 => 15,
+    };
+
+    match cipher_mode {
+        CipherMode::MemoryMode => memory_mode_len,
+        CipherMode::StreamMode => memory_mode_len - 4,
+    }
+}
+
+// reads a header back off the front of a stream, including the argon2 parameters and
+// keyslots it was written with, so decryption never needs to guess/recompute a file's cost
+// or credentials. Every field is read with `read_exact` at its known width - never more -
+// so this works equally well on a real file or on a non-seekable piped stdin. Reading stops
+// after the metadata section: the signature that `write_to_file` appends after it is not
+// read, and is left at the front of `input`, ahead of the ciphertext
+pub fn read_from_file(input: &mut impl Read) -> Result<Header> {
+    let mut magic = [0u8; 4];
+    input
+        .read_exact(&mut magic)
+        .context("Unable to read header magic bytes")?;
+    if magic != MAGIC_BYTES {
+        return Err(anyhow!("This doesn't look like a dexios-encrypted file"));
+    }
+
+    let mut type_bytes = [0u8; 3];
+    input
+        .read_exact(&mut type_bytes)
+        .context("Unable to read header type bytes")?;
+
+    let header_version = byte_to_version(type_bytes[0])?;
+    let cipher_mode = byte_to_cipher_mode(type_bytes[1])?;
+    let algorithm = byte_to_algorithm(type_bytes[2])?;
+
+    let mut salt = [0u8; SALT_LEN];
+    input.read_exact(&mut salt).context("Unable to read salt")?;
+
+    let mut nonce = vec![0u8; nonce_len_for(algorithm, cipher_mode)];
+    input.read_exact(&mut nonce).context("Unable to read nonce")?;
+
+    let mut argon_params_bytes = [0u8; ARGON_PARAMS_LEN];
+    input
+        .read_exact(&mut argon_params_bytes)
+        .context("Unable to read argon2 parameters")?;
+
+    let mut keyslots = [Keyslot::empty(); KEYSLOT_COUNT];
+    for slot in &mut keyslots {
+        let mut slot_bytes = [0u8; KEYSLOT_LEN];
+        input
+            .read_exact(&mut slot_bytes)
+            .context("Unable to read keyslot")?;
+        *slot = deserialize_keyslot(slot_bytes);
+    }
+
+    let metadata = read_metadata_section(input)?;
+
+    Ok(Header {
+        salt,
+        nonce,
+        header_type: HeaderType {
+            header_version,
+            cipher_mode,
+            algorithm,
+        },
+        argon_params: deserialize_argon_params(argon_params_bytes),
+        keyslots,
+        metadata,
+    })
+}
+
+// authenticates the header with a keyed blake3 hash, so it can't be tampered with in transit.
+// `key` must be the HKDF-derived mac subkey (see `crate::key::derive_subkeys`), not the raw
+// master key - every verifier derives the same mac subkey from `header.salt` to check it
+pub fn sign(header: &Header, key: Secret<Vec<u8>>) -> Result<Vec<u8>> {
+    let mut keyed_hash_key = [0u8; 32];
+    keyed_hash_key.copy_from_slice(&key.expose()[..32]);
+
+    let mut hasher = blake3::Hasher::new_keyed(&keyed_hash_key);
+    hash(&mut hasher, header, None);
+
+    Ok(hasher.finalize().as_bytes().to_vec())
+}
+
+// feeds every byte that `write_to_file` would write for the same `signature` argument into the
+// given hasher (the signature only when one is passed - `sign` passes `None`), so both signing
+// and the reported file hash stay in lockstep with the wire format
+pub fn hash(hasher: &mut blake3::Hasher, header: &Header, signature: Option<Vec<u8>>) {
+    hasher.update(&MAGIC_BYTES);
+    hasher.update(&[version_to_byte(header.header_type.header_version)]);
+    hasher.update(&[cipher_mode_to_byte(header.header_type.cipher_mode)]);
+    hasher.update(&[algorithm_to_byte(header.header_type.algorithm)]);
+    hasher.update(&header.salt);
+    hasher.update(&header.nonce);
+    hasher.update(&serialize_argon_params(&header.argon_params));
+
+    for slot in &header.keyslots {
+        hasher.update(&serialize_keyslot(slot));
+    }
+
+    hasher.update(&serialize_metadata(&header.metadata));
+
+    if let Some(signature) = signature {
+        hasher.update(&signature);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::global::enums::HeaderVersion;
+use crate::global::structs::ArgonParams;
+use crate::global::SALT_LEN;
+use crate::secret::Secret;
+use anyhow::{anyhow, Context, Result};
+use argon2::Argon2;
+use hkdf::Hkdf;
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use sha2::Sha256;
+
+// the cost parameters used before headers carried their own - kept only so that files
+// written before ArgonParams existed (and therefore carry no cost bytes) still decrypt
+fn legacy_params(version: &HeaderVersion) -> ArgonParams {
+    match version {
+        HeaderVersion::V1 => ArgonParams {
+            t_cost: 8,
+            m_cost: 262_144,
+            parallelism: 4,
+        },
+        HeaderVersion::V2 => ArgonParams {
+            t_cost: 10,
+            m_cost: 524_288,
+            parallelism: 4,
+        },
+        HeaderVersion::V3 | HeaderVersion::V4 | HeaderVersion::V5 => ArgonParams {
+            t_cost: 10,
+            m_cost: 1_048_576,
+            parallelism: 4,
+        },
+    }
+}
+
+// generates a random salt used for the argon2id key derivation
+pub fn gen_salt() -> [u8; SALT_LEN] {
+    let mut salt = [0u8; SALT_LEN];
+    StdRng::from_entropy().fill_bytes(&mut salt);
+    salt
+}
+
+// derives two independent 32-byte subkeys from the file's master key via HKDF-SHA256: one
+// to key the AEAD cipher that encrypts the data/metadata, and one to key the header's
+// signing hash. Previously the master key was fed to both directly, coupling two distinct
+// purposes to a single key
+pub fn derive_subkeys(
+    master_key: &Secret<Vec<u8>>,
+    salt: &[u8],
+) -> Result<(Secret<Vec<u8>>, Secret<Vec<u8>>)> {
+    let hk = Hkdf::<Sha256>::new(Some(salt), master_key.expose());
+
+    let mut aead_key = vec![0u8; 32];
+    hk.expand(b"dexios-aead-key", &mut aead_key)
+        .map_err(|_| anyhow!("Unable to derive the AEAD subkey"))?;
+
+    let mut mac_key = vec![0u8; 32];
+    hk.expand(b"dexios-header-mac", &mut mac_key)
+        .map_err(|_| anyhow!("Unable to derive the header-signing subkey"))?;
+
+    Ok((Secret::new(aead_key), Secret::new(mac_key)))
+}
+
+// generates the random 32-byte master key that actually encrypts the file's data. Each
+// keyslot just wraps a copy of this same key under a different credential
+pub fn gen_master_key() -> Secret<Vec<u8>> {
+    let mut key = vec![0u8; 32];
+    StdRng::from_entropy().fill_bytes(&mut key);
+    Secret::new(key)
+}
+
+// rejects cost parameters that argon2 would refuse, or that would produce a file nothing
+// could ever re-derive the key for
+pub fn validate_params(params: &ArgonParams) -> Result<()> {
+    if params.t_cost == 0 {
+        return Err(anyhow!("Argon2 iteration count (t_cost) can't be zero"));
+    }
+    if params.parallelism == 0 {
+        return Err(anyhow!("Argon2 parallelism can't be zero"));
+    }
+    if params.m_cost < 8 * params.parallelism as u32 {
+        return Err(anyhow!(
+            "Argon2 memory cost must be at least 8 * parallelism KiB"
+        ));
+    }
+    Ok(())
+}
+
+// hashes the raw key/password with argon2id. `params` is the cost the caller wants to use
+// (and have stored in the header); when decrypting an older file that has none, pass `None`
+// and the hardcoded parameters for that header version are used instead
+pub fn argon2_hash(
+    raw_key: Secret<Vec<u8>>,
+    salt: &[u8; SALT_LEN],
+    version: &HeaderVersion,
+    params: Option<&ArgonParams>,
+) -> Result<Secret<Vec<u8>>> {
+    let params = match params {
+        Some(params) => *params,
+        None => legacy_params(version),
+    };
+
+    validate_params(&params)?;
+
+    let mut key = vec![0u8; 32];
+
+    let argon_params = argon2::Params::new(
+        params.m_cost,
+        params.t_cost,
+        params.parallelism as u32,
+        Some(32),
+    )
+    .context("Unable to create argon2 parameters")?;
+
+    let argon2 = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, argon_params);
+
+    argon2
+        .hash_password_into(raw_key.expose(), salt, &mut key)
+        .map_err(|_| anyhow!("Unable to hash your password with argon2id"))?;
+
+    Ok(Secret::new(key))
+}
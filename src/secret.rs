@@ -0,0 +1,30 @@
+use zeroize::Zeroize;
+
+// wraps sensitive in-memory data (passwords, hashed/derived keys) so it's wiped as soon
+// as it goes out of scope, instead of lingering in memory until reallocated
+pub struct Secret<T: Zeroize>(Box<T>);
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(Box::new(value))
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+// lets callers that need to consume the same secret more than once (e.g. trying a
+// credential against every keyslot) get a fresh owned copy that's itself wrapped and
+// zeroized on drop, instead of exposing a bare clone that would linger unprotected
+impl<T: Clone + Zeroize> Clone for Secret<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.0.as_ref().clone())
+    }
+}
@@ -0,0 +1,113 @@
+use crate::global::structs::{ArgonParams, Header, HeaderType, Keyslot, KEYSLOT_COUNT};
+use crate::header::{sign, wrap_master_key};
+use crate::key::{gen_master_key, gen_salt};
+use crate::secret::Secret;
+use aead::stream::{EncryptorLE31, NewStream};
+use aes_gcm::Aes256Gcm;
+use anyhow::{anyhow, Result};
+use chacha20poly1305::XChaCha20Poly1305;
+use deoxys::DeoxysII256;
+use rand::{distributions::Standard, prelude::StdRng, Rng, SeedableRng};
+
+// the STREAM construction for each supported AEAD, so stream-mode encryption can be
+// generic over whichever algorithm the user picked
+pub enum EncryptStreams {
+    Aes256Gcm(Box<EncryptorLE31<Aes256Gcm>>),
+    XChaCha20Poly1305(Box<EncryptorLE31<XChaCha20Poly1305>>),
+    DeoxysII256(Box<EncryptorLE31<DeoxysII256>>),
+}
+
+impl EncryptStreams {
+    pub fn encrypt_next(&mut self, bytes: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            EncryptStreams::Aes256Gcm(stream) => stream.encrypt_next(bytes),
+            EncryptStreams::XChaCha20Poly1305(stream) => stream.encrypt_next(bytes),
+            EncryptStreams::DeoxysII256(stream) => stream.encrypt_next(bytes),
+        }
+        .map_err(|_| anyhow!("Unable to encrypt the next block of data"))
+    }
+
+    pub fn encrypt_last(self, bytes: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            EncryptStreams::Aes256Gcm(stream) => stream.encrypt_last(bytes),
+            EncryptStreams::XChaCha20Poly1305(stream) => stream.encrypt_last(bytes),
+            EncryptStreams::DeoxysII256(stream) => stream.encrypt_last(bytes),
+        }
+        .map_err(|_| anyhow!("Unable to encrypt the final block of data"))
+    }
+}
+
+// generates a random master key, wraps it for the supplied credential, generates the
+// nonce and builds the STREAM encryptor for the chosen algorithm, alongside the header
+// that describes it all and the header's signature
+pub fn init_encryption_stream(
+    raw_key: Secret<Vec<u8>>,
+    header_type: HeaderType,
+    argon_params: ArgonParams,
+    metadata: Option<Secret<Vec<u8>>>,
+) -> Result<(EncryptStreams, Header, Vec<u8>)> {
+    let nonce_bytes: Vec<u8> = StdRng::from_entropy()
+        .sample_iter(Standard)
+        .take(crate::header::nonce_len_for(
+            header_type.algorithm,
+            header_type.cipher_mode,
+        ))
+        .collect();
+
+    let master_key = gen_master_key();
+
+    let keyslot = wrap_master_key(&master_key, raw_key, header_type.header_version, argon_params)?;
+    let mut keyslots = [Keyslot::empty(); KEYSLOT_COUNT];
+    keyslots[0] = keyslot;
+
+    let salt = gen_salt();
+
+    // split the master key into a subkey that only ever touches the AEAD cipher, and one
+    // that only ever touches the header signature - see `crate::key::derive_subkeys`
+    let (aead_key, mac_key) = crate::key::derive_subkeys(&master_key, &salt)?;
+    drop(master_key);
+
+    let streams = match header_type.algorithm {
+        crate::global::enums::Algorithm::Aes256Gcm => {
+            let cipher = aead::NewAead::new_from_slice(aead_key.expose().as_slice())
+                .map_err(|_| anyhow!("Unable to create cipher with the derived AEAD key"))?;
+            EncryptStreams::Aes256Gcm(Box::new(EncryptorLE31::from_aead(
+                cipher,
+                nonce_bytes.as_slice().into(),
+            )))
+        }
+        crate::global::enums::Algorithm::XChaCha20Poly1305 => {
+            let cipher = aead::NewAead::new_from_slice(aead_key.expose().as_slice())
+                .map_err(|_| anyhow!("Unable to create cipher with the derived AEAD key"))?;
+            EncryptStreams::XChaCha20Poly1305(Box::new(EncryptorLE31::from_aead(
+                cipher,
+                nonce_bytes.as_slice().into(),
+            )))
+        }
+        crate::global::enums::Algorithm::DeoxysII256 => {
+            let cipher = aead::NewAead::new_from_slice(aead_key.expose().as_slice())
+                .map_err(|_| anyhow!("Unable to create cipher with the derived AEAD key"))?;
+            EncryptStreams::DeoxysII256(Box::new(EncryptorLE31::from_aead(
+                cipher,
+                nonce_bytes.as_slice().into(),
+            )))
+        }
+    };
+
+    let encrypted_metadata = metadata
+        .map(|metadata| crate::header::encrypt_metadata(metadata, &aead_key))
+        .transpose()?;
+
+    let header = Header {
+        salt,
+        nonce: nonce_bytes,
+        header_type,
+        argon_params,
+        keyslots,
+        metadata: encrypted_metadata,
+    };
+
+    let signature = sign(&header, mac_key)?;
+
+    Ok((streams, header, signature))
+}
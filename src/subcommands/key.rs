@@ -0,0 +1,106 @@
+use crate::global::structs::{ArgonParams, KEYSLOT_COUNT};
+use crate::header::{find_empty_keyslot, read_from_file, unwrap_master_key, write_to_file};
+use crate::secret::Secret;
+use anyhow::{anyhow, Context, Result};
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom};
+
+// opens the header of an already-encrypted file, unwraps its master key with one known
+// credential, and writes an additional keyslot for a new credential. The ciphertext body
+// is never read or rewritten
+pub fn add_key(
+    input: &str,
+    existing_credential: Secret<Vec<u8>>,
+    new_credential: Secret<Vec<u8>>,
+    argon_params: ArgonParams,
+) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(input)
+        .context("Unable to open the file to add a key to")?;
+
+    let mut header = read_from_file(&mut file)?;
+
+    let master_key = unwrap_master_key(
+        &header.keyslots,
+        existing_credential,
+        header.header_type.header_version,
+    )
+    .context("Unable to unlock this file with the provided credential")?;
+
+    let slot_index = find_empty_keyslot(&header.keyslots)?;
+    header.keyslots[slot_index] = crate::header::wrap_master_key(
+        &master_key,
+        new_credential,
+        header.header_type.header_version,
+        argon_params,
+    )?;
+
+    // the header is signed with the HKDF-derived mac subkey, not the master key directly -
+    // see `crate::key::derive_subkeys`
+    let (_, mac_key) = crate::key::derive_subkeys(&master_key, &header.salt)?;
+    let signature = crate::header::sign(&header, mac_key)?;
+
+    file.seek(SeekFrom::Start(0))
+        .context("Unable to seek to the start of the file")?;
+    write_to_file(
+        &mut crate::global::enums::OutputFile::File(file),
+        &header,
+        Some(signature),
+    )?;
+
+    Ok(())
+}
+
+// blanks an existing keyslot after confirming the supplied credential can currently unlock
+// the file, so only someone who can already open it can remove a slot. The credential may
+// unlock any slot - it need not be the one at `slot_index`
+pub fn remove_key(input: &str, credential: Secret<Vec<u8>>, slot_index: usize) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(input)
+        .context("Unable to open the file to remove a key from")?;
+
+    if slot_index >= KEYSLOT_COUNT {
+        return Err(anyhow!(
+            "Invalid keyslot index {} - must be between 0 and {}",
+            slot_index,
+            KEYSLOT_COUNT - 1
+        ));
+    }
+
+    let mut header = read_from_file(&mut file)?;
+
+    let master_key = unwrap_master_key(&header.keyslots, credential, header.header_type.header_version)
+        .context("Unable to unlock this file with the provided credential")?;
+
+    if header.keyslots[slot_index].is_empty() {
+        return Err(anyhow!("Keyslot {} is already empty", slot_index));
+    }
+
+    // refusing to blank the only remaining slot stops a user from locking themselves out
+    // of a file they can currently unlock
+    let remaining_slots = header.keyslots.iter().filter(|slot| !slot.is_empty()).count();
+    if remaining_slots <= 1 {
+        return Err(anyhow!(
+            "Refusing to remove the last remaining keyslot - this would make the file permanently undecryptable"
+        ));
+    }
+
+    header.keyslots[slot_index] = crate::global::structs::Keyslot::empty();
+
+    let (_, mac_key) = crate::key::derive_subkeys(&master_key, &header.salt)?;
+    let signature = crate::header::sign(&header, mac_key)?;
+
+    file.seek(SeekFrom::Start(0))
+        .context("Unable to seek to the start of the file")?;
+    write_to_file(
+        &mut crate::global::enums::OutputFile::File(file),
+        &header,
+        Some(signature),
+    )?;
+
+    Ok(())
+}
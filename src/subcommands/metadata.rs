@@ -0,0 +1,26 @@
+use crate::header::{decrypt_metadata, read_from_file, unwrap_master_key};
+use crate::secret::Secret;
+use anyhow::{Context, Result};
+use std::fs::File;
+
+// reads and decrypts a file's metadata block without streaming (or even opening for
+// writing) the ciphertext body that follows the header
+pub fn read_metadata(input: &str, credential: Secret<Vec<u8>>) -> Result<Vec<u8>> {
+    let mut file = File::open(input).context("Unable to open the file to read metadata from")?;
+
+    let header = read_from_file(&mut file)?;
+
+    let metadata = header
+        .metadata
+        .as_ref()
+        .context("This file doesn't have a metadata section")?;
+
+    let master_key = unwrap_master_key(&header.keyslots, credential, header.header_type.header_version)
+        .context("Unable to unlock this file with the provided credential")?;
+
+    // metadata is encrypted under the HKDF-derived AEAD subkey, not the master key directly
+    // - see `crate::key::derive_subkeys`
+    let (aead_key, _) = crate::key::derive_subkeys(&master_key, &header.salt)?;
+
+    decrypt_metadata(metadata, &aead_key)
+}